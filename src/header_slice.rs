@@ -0,0 +1,316 @@
+//! Packed header+slice payload, in the style of servo_arc's `HeaderSlice`.
+//!
+//! `CowArcHeaderSlice<H, I>` owns a single allocation holding a fixed `H`
+//! header followed inline by a run of `I` items, instead of forcing callers
+//! to box a `Vec<I>` inside their value. It is a plain `Clone` value type
+//! (the clone deep-copies the whole packed allocation), so it can be used
+//! as the `T` of a [`crate::CowArc`] like any other value:
+//! `CowArc::new(CowArcHeaderSlice::from_header_and_iter(header, items))`.
+
+use std::alloc::{alloc, dealloc, handle_alloc_error, Layout};
+use std::mem;
+use std::ptr::{self, NonNull};
+
+#[repr(C)]
+struct CowArcHeaderSliceInner<H, I> {
+    header: H,
+    data: [I],
+}
+
+/// Unwind-safe cleanup for a [`CowArcHeaderSlice`] allocation while it is
+/// only partially initialized; defused once construction succeeds.
+struct RawGuard<H, I> {
+    base: *mut u8,
+    layout: Layout,
+    header_written: bool,
+    items_written: usize,
+    data_offset: usize,
+    item_size: usize,
+    _header: std::marker::PhantomData<H>,
+    _item: std::marker::PhantomData<I>,
+}
+impl<H, I> Drop for RawGuard<H, I> {
+    fn drop(&mut self) {
+        unsafe {
+            if self.items_written > 0 {
+                let data = self.base.add(self.data_offset);
+                for i in 0..self.items_written {
+                    ptr::drop_in_place(data.add(i * self.item_size) as *mut I);
+                }
+            }
+            if self.header_written {
+                ptr::drop_in_place(self.base as *mut H);
+            }
+            if self.layout.size() > 0 {
+                dealloc(self.base, self.layout);
+            }
+        }
+    }
+}
+
+/// Owns a single allocation storing a header `H` followed inline by `I`
+/// items. See the module docs for how this is meant to be combined with
+/// [`crate::CowArc`].
+pub struct CowArcHeaderSlice<H, I> {
+    ptr: NonNull<CowArcHeaderSliceInner<H, I>>,
+}
+
+// SAFETY: `CowArcHeaderSlice` uniquely owns its allocation, same as `Box`.
+unsafe impl<H: Send, I: Send> Send for CowArcHeaderSlice<H, I> {}
+unsafe impl<H: Sync, I: Sync> Sync for CowArcHeaderSlice<H, I> {}
+
+impl<H, I> CowArcHeaderSlice<H, I> {
+    /// Layout of the combined allocation for `len` items, plus the byte
+    /// offset at which the item slice starts.
+    fn layout_and_offset(len: usize) -> (Layout, usize) {
+        let header_layout = Layout::new::<H>();
+        let data_layout = Layout::array::<I>(len)
+            .expect("CowArcHeaderSlice: item slice layout overflows isize");
+        let (combined, data_offset) = header_layout
+            .extend(data_layout)
+            .expect("CowArcHeaderSlice: combined layout overflows isize");
+        (combined.pad_to_align(), data_offset)
+    }
+
+    /// Builds a `CowArcHeaderSlice` from a header and an iterator of items,
+    /// writing both into one allocation sized from `iter`'s exact length.
+    ///
+    /// If `iter` panics partway through (for example an item's `Clone`
+    /// panicking), or yields fewer items than its reported length, every
+    /// item written so far (and the header) is dropped and the allocation
+    /// is freed before the panic continues to unwind.
+    /// # Examples
+    /// ```
+    /// use cow_arc::{CowArc, CowArcHeaderSlice};
+    ///
+    /// let packed = CowArcHeaderSlice::from_header_and_iter("three ints".to_owned(), vec![1, 2, 3].into_iter());
+    /// let mut source = CowArc::new(packed);
+    ///
+    /// assert_eq!(source.header().as_str(), "three ints");
+    /// assert_eq!(source.slice(), [1, 2, 3]);
+    ///
+    /// // Copy-on-write still applies: a shared clone forces a deep copy
+    /// // of the whole packed allocation on the next mutation.
+    /// let mut shared = source.clone();
+    /// shared.update_val(|packed| {
+    ///     let new = CowArcHeaderSlice::from_header_and_iter(packed.header().clone(), vec![1, 2, 3, 4].into_iter());
+    ///     *packed = new;
+    /// });
+    /// assert_eq!(source.slice(), [1, 2, 3]);
+    /// assert_eq!(shared.slice(), [1, 2, 3, 4]);
+    /// ```
+    pub fn from_header_and_iter<It>(header: H, mut iter: It) -> Self
+    where
+        It: ExactSizeIterator<Item = I>,
+    {
+        let len = iter.len();
+        let (layout, data_offset) = Self::layout_and_offset(len);
+
+        // A zero-sized layout (ZST header, zero items) has no backing
+        // allocation; `alloc` does not support size-0 layouts, so use a
+        // dangling, correctly-aligned pointer instead, same as `Box<[T; 0]>`.
+        let base = if layout.size() == 0 {
+            layout.align() as *mut u8
+        } else {
+            // SAFETY: `layout` has non-zero size.
+            let base = unsafe { alloc(layout) };
+            if base.is_null() {
+                handle_alloc_error(layout);
+            }
+            base
+        };
+
+        let mut guard = RawGuard::<H, I> {
+            base,
+            layout,
+            header_written: false,
+            items_written: 0,
+            data_offset,
+            item_size: mem::size_of::<I>(),
+            _header: std::marker::PhantomData,
+            _item: std::marker::PhantomData,
+        };
+
+        // SAFETY: `base` is valid for `layout` and properly aligned for `H`.
+        unsafe {
+            ptr::write(base as *mut H, header);
+        }
+        guard.header_written = true;
+
+        // SAFETY: `data_offset..data_offset + len * size_of::<I>()` is
+        // within `layout` and properly aligned for `I`.
+        let data_ptr = unsafe { base.add(data_offset) } as *mut I;
+        // Drive the loop off the pre-allocated `len`, never off the
+        // iterator: `ExactSizeIterator::len()` is only a hint and must
+        // never be relied on for memory safety, so an iterator that
+        // under- or over-yields relative to the `len` it reported must
+        // not make this write past the allocation or leave part of the
+        // trailing slice uninitialized. Under-yield panics (caught by
+        // `RawGuard`); over-yield just leaves the extra items undrained.
+        for i in 0..len {
+            let item = iter
+                .next()
+                .expect("ExactSizeIterator yielded fewer than len items");
+            unsafe {
+                ptr::write(data_ptr.add(i), item);
+            }
+            guard.items_written = i + 1;
+        }
+
+        // A `repr(C)` struct's fat-pointer metadata equals its trailing
+        // DST field's metadata, so casting a `*mut [I]` built from this
+        // allocation's *start* carries `len` over to the struct pointer
+        // while still addressing the whole allocation (header included).
+        let fat: *mut [I] = ptr::slice_from_raw_parts_mut(base as *mut I, len);
+        let inner_ptr = fat as *mut CowArcHeaderSliceInner<H, I>;
+
+        mem::forget(guard);
+
+        CowArcHeaderSlice {
+            // SAFETY: `inner_ptr` was derived from the non-null `base`.
+            ptr: unsafe { NonNull::new_unchecked(inner_ptr) },
+        }
+    }
+
+    fn inner(&self) -> &CowArcHeaderSliceInner<H, I> {
+        // SAFETY: `self.ptr` always points at a fully-initialized, live
+        // allocation for the lifetime of this `CowArcHeaderSlice`.
+        unsafe { self.ptr.as_ref() }
+    }
+
+    /// Reference to the packed header.
+    pub fn header(&self) -> &H {
+        &self.inner().header
+    }
+
+    /// Reference to the packed item slice.
+    pub fn slice(&self) -> &[I] {
+        &self.inner().data
+    }
+
+    /// `(&H, &[I])` view of the whole packed allocation.
+    pub fn header_and_slice(&self) -> (&H, &[I]) {
+        let inner = self.inner();
+        (&inner.header, &inner.data)
+    }
+}
+
+impl<H: Clone, I: Clone> Clone for CowArcHeaderSlice<H, I> {
+    /// Deep-copies the whole packed allocation into a fresh one, so that
+    /// copy-on-write still works when a `CowArcHeaderSlice` is used as the
+    /// `T` of a [`crate::CowArc`].
+    fn clone(&self) -> Self {
+        let (header, slice) = self.header_and_slice();
+        Self::from_header_and_iter(header.clone(), slice.iter().cloned())
+    }
+}
+
+impl<H, I> Drop for CowArcHeaderSlice<H, I> {
+    fn drop(&mut self) {
+        let len = self.slice().len();
+        let (layout, _) = Self::layout_and_offset(len);
+        unsafe {
+            // Drops the header and every item in the trailing slice.
+            ptr::drop_in_place(self.ptr.as_ptr());
+            if layout.size() > 0 {
+                dealloc(self.ptr.as_ptr() as *mut u8, layout);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use std::cell::RefCell;
+    use std::panic;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_data_offset_matches_item_alignment_padding(){
+        // align_of::<u64>() (8) > align_of::<u8>() (1), so the 1-byte
+        // header needs 7 bytes of padding before the item slice starts.
+        let len = 3;
+        let (_, data_offset) = CowArcHeaderSlice::<u8, u64>::layout_and_offset(len);
+        assert_eq!(data_offset, std::mem::align_of::<u64>());
+
+        let packed = CowArcHeaderSlice::<u8, u64>::from_header_and_iter(7u8, vec![1u64, 2, 3].into_iter());
+        let header_addr = packed.header() as *const u8 as usize;
+        let data_addr = packed.slice().as_ptr() as usize;
+
+        assert_eq!(data_addr - header_addr, data_offset);
+        assert_eq!(data_addr % std::mem::align_of::<u64>(), 0);
+        assert!(packed.header().eq(&7));
+        assert!(packed.slice().eq(&[1u64, 2, 3]));
+    }
+
+    #[test]
+    fn test_zero_length_and_zst_allocations(){
+        {
+            // Zero-sized header, zero items: hits the dangling-pointer,
+            // no-backing-allocation branch.
+            let packed = CowArcHeaderSlice::<(), ()>::from_header_and_iter((), std::iter::empty());
+            assert!(packed.header().eq(&()));
+            assert!(packed.slice().is_empty());
+        }
+
+        {
+            // Non-ZST header, zero items.
+            let packed = CowArcHeaderSlice::<u32, u8>::from_header_and_iter(42, Vec::new().into_iter());
+            assert!(packed.header().eq(&42));
+            assert!(packed.slice().is_empty());
+        }
+
+        {
+            // Zero-sized header, some items.
+            let packed = CowArcHeaderSlice::<(), i32>::from_header_and_iter((), vec![1, 2, 3].into_iter());
+            assert!(packed.slice().eq(&[1, 2, 3]));
+        }
+    }
+
+    /// Item whose drop is observable, to assert on what `RawGuard` cleaned
+    /// up after a panic partway through construction.
+    struct DropRecorder(i32, Rc<RefCell<Vec<i32>>>);
+    impl Drop for DropRecorder {
+        fn drop(&mut self) {
+            self.1.borrow_mut().push(self.0);
+        }
+    }
+
+    /// Reports `len() == 3` but panics while producing the 2nd item.
+    struct PanicOnSecondItem {
+        produced: i32,
+        log: Rc<RefCell<Vec<i32>>>,
+    }
+    impl Iterator for PanicOnSecondItem {
+        type Item = DropRecorder;
+        fn next(&mut self) -> Option<DropRecorder> {
+            self.produced += 1;
+            if self.produced == 2 {
+                panic!("boom while producing the 2nd item");
+            }
+            Some(DropRecorder(self.produced, self.log.clone()))
+        }
+    }
+    impl ExactSizeIterator for PanicOnSecondItem {
+        fn len(&self) -> usize {
+            3
+        }
+    }
+
+    #[test]
+    fn test_panic_mid_construction_drops_written_items(){
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let iter = PanicOnSecondItem{ produced: 0, log: log.clone() };
+
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            CowArcHeaderSlice::<i32, DropRecorder>::from_header_and_iter(99, iter)
+        }));
+
+        assert!(result.is_err());
+        // Only the one item already written before the panic gets dropped
+        // by `RawGuard`; the header (plain `i32`, no custom drop) and the
+        // un-produced 2nd/3rd items leave no trace.
+        assert_eq!(*log.borrow(), vec![1]);
+    }
+}
@@ -2,13 +2,20 @@
 //! It saves some RAM by sharing immutable values between CowArc clones.
 //! Memory allocates only in case of changing value.
 //! CowArc can be usefull for creating builders.
+//! [`CowArcHeaderSlice`] additionally lets a CowArc wrap a packed
+//! header-plus-items payload in a single allocation, instead of boxing a `Vec`.
+
+mod header_slice;
+pub use header_slice::CowArcHeaderSlice;
 
 use std::{
     sync::{
-        Arc
+        Arc,
+        Weak
     },
     ops::{
-        Deref
+        Deref,
+        DerefMut
     }
 };
 
@@ -48,8 +55,47 @@ impl<T: Clone> CowArc<T> {
         self.inner = Arc::new(val);
     }
 
+    /// Returns a mutable reference into the inner value, cloning the
+    /// underlying allocation only if it is currently shared.
+    ///
+    /// This mirrors `Arc::make_mut`: if this `CowArc` is the unique owner
+    /// of its allocation (no other clones and no live `CowWeak` handles),
+    /// the existing allocation is reused and no copying happens at all.
+    /// Otherwise the inner value is cloned into a fresh `Arc` before
+    /// returning the mutable reference, so previously taken clones keep
+    /// observing the old value. After `make_mut` returns, this `CowArc`
+    /// is guaranteed to be the unique owner of its allocation.
+    /// # Examples
+    /// ```
+    /// use cow_arc::CowArc;
+    /// use std::ops::Deref;
+    ///
+    /// // No other clones around: mutates in place, no new allocation.
+    /// let mut unique = CowArc::new(vec![1, 2, 3]);
+    /// let ptr_before: *const Vec<i32> = unique.deref();
+    /// unique.make_mut().push(4);
+    /// let ptr_after: *const Vec<i32> = unique.deref();
+    /// assert!(std::ptr::eq(ptr_before, ptr_after));
+    /// assert!(unique.eq(&vec![1, 2, 3, 4]));
+    ///
+    /// // A live clone forces a copy on the next mutation.
+    /// let source = CowArc::new(vec![1, 2, 3]);
+    /// let mut shared = source.clone();
+    /// shared.make_mut().push(4);
+    /// assert!(std::ptr::eq(source.deref(), shared.deref()) == false);
+    /// assert!(source.eq(&vec![1, 2, 3]));
+    /// assert!(shared.eq(&vec![1, 2, 3, 4]));
+    /// ```
+    pub fn make_mut(&mut self) -> &mut T {
+        if Arc::strong_count(&self.inner) > 1 || Arc::weak_count(&self.inner) > 0 {
+            self.inner = Arc::new(self.inner.deref().clone());
+        }
+        Arc::get_mut(&mut self.inner).expect("CowArc must be the unique owner after the check above")
+    }
+
     /// Method updates inner Arc value by replacing it with new value.
-    /// Performs new allocation.
+    /// Reuses the existing allocation in place when this `CowArc` is the
+    /// unique owner, and only allocates when the value is actually shared.
     /// All previous values are still available over previous clones.
     /// # Examples
     /// ```
@@ -57,23 +103,178 @@ impl<T: Clone> CowArc<T> {
     /// use std::ops::Deref;
     ///
     /// let source = CowArc::new(vec![1, 2, 3]);
-    /// 
+    ///
     /// // Still shared memory
     /// let mut updated = source.clone();
     /// assert!(std::ptr::eq(source.deref(), updated.deref()) == true);
     /// assert!(updated.eq(&vec![1, 2, 3]));
-    /// 
-    /// // New memory allocation
+    ///
+    /// // New memory allocation, since `source` still holds the old one
     /// updated.update_val(|val|{
     ///        val.push(4);
     /// });
     /// assert!(std::ptr::eq(source.deref(), updated.deref()) == false);
     /// assert!(updated.eq(&vec![1, 2, 3, 4]));
+    ///
+    /// // No other clones left: `update_val` now mutates in place.
+    /// let before: *const Vec<i32> = updated.deref();
+    /// updated.update_val(|val|{
+    ///        val.push(5);
+    /// });
+    /// let after: *const Vec<i32> = updated.deref();
+    /// assert!(std::ptr::eq(before, after));
+    /// assert!(updated.eq(&vec![1, 2, 3, 4, 5]));
     /// ```
     pub fn update_val<F: FnOnce(&mut T)>(&mut self, f: F) {
-        let mut v: T = self.inner.deref().clone();
-        f(&mut v);
-        self.inner = Arc::new(v);
+        f(self.make_mut());
+    }
+
+    /// Returns a `MutableGuard` that allows editing the inner value through
+    /// plain `&mut T` access (field assignments, method calls, ...) instead
+    /// of passing a closure to [`CowArc::update_val`].
+    ///
+    /// The guard borrows this `CowArc` mutably, so only one guard can be
+    /// alive at a time. Every `DerefMut` access goes through [`CowArc::make_mut`],
+    /// so the allocation is only cloned on the first mutation and reused for
+    /// the rest of the guard's lifetime, even across several statements.
+    /// # Examples
+    /// ```
+    /// use cow_arc::CowArc;
+    /// use std::ops::Deref;
+    ///
+    /// let source = CowArc::new(vec![1, 2, 3]);
+    /// let mut changed = source.clone();
+    ///
+    /// {
+    ///     let mut guard = changed.lock_mut();
+    ///     guard.push(4);
+    ///     guard.push(5);
+    /// }
+    ///
+    /// assert!(std::ptr::eq(source.deref(), changed.deref()) == false);
+    /// assert!(source.eq(&vec![1, 2, 3]));
+    /// assert!(changed.eq(&vec![1, 2, 3, 4, 5]));
+    /// ```
+    pub fn lock_mut(&mut self) -> MutableGuard<'_, T> {
+        MutableGuard{
+            owner: self
+        }
+    }
+
+    /// Creates a non-owning `CowWeak` handle to the allocation this `CowArc`
+    /// currently points at.
+    ///
+    /// A `CowWeak` tracks that specific allocation, not the logical "slot" of
+    /// the `CowArc` it was created from: once `set_val`/`update_val`/`make_mut`
+    /// publishes a new allocation, this `CowArc` moves on to it, but the
+    /// `CowWeak` keeps pointing at the old value and will only upgrade for as
+    /// long as some other `CowArc` clone of that old value is still alive.
+    /// # Examples
+    /// ```
+    /// use cow_arc::CowArc;
+    ///
+    /// let mut source = CowArc::new(vec![1, 2, 3]);
+    /// let weak = source.downgrade();
+    /// assert!(weak.upgrade().is_some());
+    ///
+    /// // Still reachable through `source`'s clone of the old allocation.
+    /// let kept_alive = source.clone();
+    /// source.set_val(vec![4, 5, 6]);
+    /// assert!(weak.upgrade().unwrap().eq(&vec![1, 2, 3]));
+    ///
+    /// // Once every CowArc referencing the old value is gone, it's gone too.
+    /// drop(kept_alive);
+    /// assert!(weak.upgrade().is_none());
+    /// ```
+    pub fn downgrade(&self) -> CowWeak<T> {
+        CowWeak{
+            inner: Arc::downgrade(&self.inner)
+        }
+    }
+
+    /// Returns a raw pointer to the inner value without consuming or
+    /// affecting the refcount of this `CowArc`.
+    ///
+    /// The returned pointer is only valid for as long as this `CowArc` (or
+    /// one of its clones) is alive, and must **not** be passed to
+    /// [`CowArc::from_raw`] since it does not carry an owning reference.
+    pub fn as_ptr(&self) -> *const T {
+        Arc::as_ptr(&self.inner)
+    }
+
+    /// Consumes the `CowArc`, returning a raw pointer to the inner value.
+    ///
+    /// The pointer represents an owning reference to the allocation: it
+    /// must eventually be passed to exactly one [`CowArc::from_raw`] call to
+    /// avoid leaking the allocation, and must not be used after that.
+    /// # Examples
+    /// ```
+    /// use cow_arc::CowArc;
+    ///
+    /// let source = CowArc::new(vec![1, 2, 3]);
+    /// let ptr = source.into_raw();
+    /// let restored = unsafe { CowArc::from_raw(ptr) };
+    /// assert!(restored.eq(&vec![1, 2, 3]));
+    /// ```
+    pub fn into_raw(self) -> *const T {
+        Arc::into_raw(self.inner)
+    }
+
+    /// Reconstructs a `CowArc` from a raw pointer previously obtained from
+    /// [`CowArc::into_raw`].
+    ///
+    /// # Safety
+    /// `ptr` must have been obtained from a previous call to
+    /// [`CowArc::into_raw`], and exactly one `from_raw` must be used to
+    /// balance each `into_raw`; calling it more than once for the same
+    /// pointer, or on a pointer from [`CowArc::as_ptr`], causes a double
+    /// free or otherwise invalid refcount.
+    pub unsafe fn from_raw(ptr: *const T) -> CowArc<T> {
+        CowArc{
+            inner: unsafe { Arc::from_raw(ptr) }
+        }
+    }
+}
+
+/// Non-owning handle to the allocation a [`CowArc`] pointed at when
+/// [`CowArc::downgrade`] was called. See `downgrade` for how this interacts
+/// with `set_val`/`update_val`/`make_mut`.
+#[derive(Debug)]
+pub struct CowWeak<T: Clone>{
+    inner: Weak<T>
+}
+impl<T: Clone> CowWeak<T> {
+    /// Attempts to upgrade back to a `CowArc`, returning `None` if the
+    /// allocation this handle points at has already been dropped.
+    pub fn upgrade(&self) -> Option<CowArc<T>> {
+        self.inner.upgrade().map(|inner| CowArc{ inner })
+    }
+}
+impl<T: Clone> Clone for CowWeak<T>{
+    fn clone(&self) -> Self {
+        CowWeak{
+            inner: self.inner.clone()
+        }
+    }
+}
+
+/// Guard returned by [`CowArc::lock_mut`] that exposes the inner value as
+/// plain `&mut T`. Dropping the guard does not need to do any work itself:
+/// every mutable access already publishes through the owning `CowArc` via
+/// [`CowArc::make_mut`], so the new allocation (if any) is visible as soon
+/// as it happens, not just when the guard goes out of scope.
+pub struct MutableGuard<'a, T: Clone>{
+    owner: &'a mut CowArc<T>
+}
+impl<'a, T: Clone> Deref for MutableGuard<'a, T>{
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        self.owner.deref()
+    }
+}
+impl<'a, T: Clone> DerefMut for MutableGuard<'a, T>{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.owner.make_mut()
     }
 }
 impl<T: Clone> Deref for CowArc<T>{
@@ -143,4 +344,40 @@ mod tests{
             assert!(updated.eq(&vec![1, 2, 3, 4, 5]));
         }
     }
+
+    #[test]
+    fn test_lock_mut_guard(){
+        {
+            // Dropping a guard without mutating it must not reallocate
+            // or change the value.
+            let mut value = CowArc::new(vec![1, 2, 3]);
+            let value_ptr: *const Vec<i32> = value.deref();
+            {
+                let _guard = value.lock_mut();
+            }
+            let value_ptr_after: *const Vec<i32> = value.deref();
+
+            assert!(std::ptr::eq(value_ptr, value_ptr_after));
+            assert!(value.eq(&vec![1, 2, 3]));
+        }
+
+        {
+            // Mutating through the guard still publishes exactly one new
+            // allocation, leaving clones taken before the guard untouched.
+            let source = CowArc::new(vec![1, 2, 3]);
+            let mut changed = source.clone();
+            {
+                let mut guard = changed.lock_mut();
+                guard.push(4);
+                guard.push(5);
+            }
+
+            let source_ptr: &Vec<i32> = source.deref();
+            let changed_ptr: &Vec<i32> = changed.deref();
+
+            assert!(std::ptr::eq(source_ptr, changed_ptr) == false);
+            assert!(source.eq(&vec![1, 2, 3]));
+            assert!(changed.eq(&vec![1, 2, 3, 4, 5]));
+        }
+    }
 }
\ No newline at end of file